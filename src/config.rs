@@ -0,0 +1,38 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    pub server: ServerConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerConfig {
+    pub target: String,
+    pub proxy: Option<String>,
+    /// Resolution mode: "odoh" (default), "doh", or "plain".
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Resolver address used for plain DNS queries, e.g. "8.8.8.8:53".
+    #[serde(default)]
+    pub resolver: Option<String>,
+    /// How long a fetched ODoH config is trusted before being proactively
+    /// refreshed. Defaults to 3600 seconds.
+    #[serde(default)]
+    pub config_ttl_secs: Option<u64>,
+    /// Pad the plaintext DNS query up to a multiple of this many bytes
+    /// before encryption. Defaults to 128 bytes.
+    #[serde(default)]
+    pub padding_block: Option<usize>,
+}
+
+impl Config {
+    pub fn from_path(path: &str) -> Result<Config> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path))?;
+        let config: Config = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config file {}", path))?;
+        Ok(config)
+    }
+}
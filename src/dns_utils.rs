@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Context, Result};
+use rand::random;
+use reqwest::{
+    header::{ACCEPT, CONTENT_TYPE},
+    Client, StatusCode,
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+use trust_dns_proto::{
+    op::{Message, MessageType, OpCode, Query},
+    rr::{Name, RecordType},
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+
+const ODOH_CONFIG_PATH: &str = "/.well-known/odohconfigs";
+const DOH_QUERY_PATH: &str = "/dns-query";
+const DOH_CONTENT_TYPE: &str = "application/dns-message";
+const PLAIN_DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Fetch the published ODoH configuration set from the target resolver, as
+/// raw wire bytes ready for `odoh_rs::protocol::get_supported_config`.
+pub async fn fetch_odoh_config(target: &str) -> Result<Vec<u8>> {
+    let url = format!("{}{}", target.trim_end_matches('/'), ODOH_CONFIG_PATH);
+    let bytes = Client::new().get(&url).send().await?.bytes().await?;
+    Ok(bytes.to_vec())
+}
+
+/// Build a wire-format DNS query message for `domain`/`qtype`, with a
+/// randomized transaction ID so repeated lookups aren't trivially linkable.
+pub fn create_dns_query(domain: &str, qtype: &str) -> Result<Vec<u8>> {
+    let mut msg = Message::new();
+    msg.set_id(random::<u16>())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true);
+    let name = Name::from_ascii(domain).context("invalid domain name")?;
+    let record_type = qtype
+        .parse::<RecordType>()
+        .map_err(|_| anyhow!("unsupported query type: {}", qtype))?;
+    msg.add_query(Query::query(name, record_type));
+    msg.to_bytes().context("failed to encode DNS query")
+}
+
+/// Parse a raw DNS wire message into a `trust_dns_proto::op::Message`.
+pub fn parse_dns_answer(raw: &[u8]) -> Result<Message> {
+    Message::from_bytes(raw).context("failed to parse DNS response")
+}
+
+/// Send a standard (non-oblivious) DoH query: the raw DNS wire message is
+/// POSTed to the target's `/dns-query` with `application/dns-message`.
+pub async fn query_doh(target: &str, dns_msg: &[u8]) -> Result<Vec<u8>> {
+    let url = format!("{}{}", target.trim_end_matches('/'), DOH_QUERY_PATH);
+    let resp = Client::new()
+        .post(&url)
+        .header(CONTENT_TYPE, DOH_CONTENT_TYPE)
+        .header(ACCEPT, DOH_CONTENT_TYPE)
+        .body(dns_msg.to_vec())
+        .send()
+        .await?;
+    if resp.status() != StatusCode::OK {
+        return Err(anyhow!(
+            "DoH query failed with response status code {}",
+            resp.status().as_u16()
+        ));
+    }
+    Ok(resp.bytes().await?.to_vec())
+}
+
+/// Send a classic DNS query over UDP to `resolver`.
+pub async fn query_plain(resolver: SocketAddr, dns_msg: &[u8]) -> Result<Vec<u8>> {
+    let mut socket = UdpSocket::bind(("0.0.0.0", 0))
+        .await
+        .context("failed to bind UDP socket")?;
+    socket.connect(resolver).await?;
+    socket.send(dns_msg).await?;
+
+    let mut buf = [0u8; 4096];
+    let len = timeout(PLAIN_DNS_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .context("plain DNS query timed out")??;
+    Ok(buf[..len].to_vec())
+}
@@ -1,20 +1,32 @@
 pub mod config;
 pub mod dns_utils;
 mod odoh;
+mod proxy;
 
-use anyhow::Result;
-use clap::{App, Arg};
+use anyhow::{Context, Result};
+use clap::{App, Arg, SubCommand};
 use config::Config;
 
-use crate::odoh::ODOHSession;
+use crate::dns_utils::{create_dns_query, parse_dns_answer, query_doh, query_plain};
+use crate::odoh::{Mode, ODOHSession};
+use crate::proxy::ProxyServer;
+use futures::future::join_all;
+use serde::Serialize;
 use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+use trust_dns_proto::op::Message;
+
+const DEFAULT_PLAIN_RESOLVER: &str = "8.8.8.8:53";
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 const PKG_DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
+const DEFAULT_PROXY_PATH: &str = "/proxy";
 
-#[tokio::main(core_threads = 1, max_threads = 1)]
+#[tokio::main(core_threads = 4, max_threads = 8)]
 async fn main() -> Result<()> {
     let matches = App::new(PKG_NAME)
         .version(PKG_VERSION)
@@ -31,31 +43,223 @@ async fn main() -> Result<()> {
         .arg(
             Arg::with_name("domain")
                 .help("Domain to query")
-                .required(true)
                 .index(1),
         )
+        .arg(Arg::with_name("type").help("Query type").index(2))
         .arg(
-            Arg::with_name("type")
-                .help("Query type")
-                .required(true)
-                .index(2),
+            Arg::with_name("queries_file")
+                .long("queries-file")
+                .value_name("FILE")
+                .help("Path to a file of \"domain type\" pairs, one per line, resolved concurrently")
+                .takes_value(true)
+                .conflicts_with_all(&["domain", "type"]),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .value_name("MODE")
+                .help("Resolution mode: odoh (default), doh, or plain")
+                .possible_values(&["odoh", "doh", "plain"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("padding_block")
+                .long("padding-block")
+                .value_name("BYTES")
+                .help("Pad the plaintext DNS query up to a multiple of this many bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .help("Output format: text (default) or json")
+                .possible_values(&["text", "json"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("allow_insecure_fallback")
+                .long("allow-insecure-fallback")
+                .help(
+                    "Allow degrading to a weaker protocol (ODoH -> DoH -> plain DNS) when the \
+                     stronger one is unavailable. Without this flag, a failure is returned \
+                     instead of silently downgrading. Has no effect with --mode doh or \
+                     --mode plain, which never cascade further.",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("proxy")
+                .about("Run an oblivious proxy (relay) server between ODoH clients and targets")
+                .arg(
+                    Arg::with_name("listen")
+                        .short("l")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .help("Address to listen on, e.g. 0.0.0.0:8080")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .short("p")
+                        .long("path")
+                        .value_name("PATH")
+                        .help("URI path the proxy listens on")
+                        .takes_value(true)
+                        .default_value(DEFAULT_PROXY_PATH),
+                ),
         )
         .get_matches();
 
+    if let Some(proxy_matches) = matches.subcommand_matches("proxy") {
+        let listen: SocketAddr = proxy_matches
+            .value_of("listen")
+            .unwrap()
+            .parse()
+            .context("invalid --listen address")?;
+        let path = proxy_matches.value_of("path").unwrap_or(DEFAULT_PROXY_PATH);
+        return ProxyServer::new(listen, path.to_string()).run().await;
+    }
+
     let config_file = matches
         .value_of("config_file")
         .unwrap_or("tests/config.toml");
-    let config = Config::from_path(config_file)?;
-    let domain = matches.value_of("domain").unwrap();
-    let qtype = matches.value_of("type").unwrap();
+    let mut config = Config::from_path(config_file)?;
+    if let Some(padding_block) = matches.value_of("padding_block") {
+        config.server.padding_block = Some(
+            padding_block
+                .parse()
+                .context("--padding-block must be a byte count")?,
+        );
+    }
+    let queries = if let Some(path) = matches.value_of("queries_file") {
+        read_queries_file(path)?
+    } else {
+        let domain = matches
+            .value_of("domain")
+            .context("domain is required unless --queries-file or `proxy` is used")?;
+        let qtype = matches
+            .value_of("type")
+            .context("query type is required unless --queries-file or `proxy` is used")?;
+        vec![(domain.to_string(), qtype.to_string())]
+    };
 
-    let session = ODOHSession::new(
-        config.server.target.as_str(),
-        config.server.proxy.as_ref().map(|v| v.as_str()),
-    )
-    .await?;
+    let mode = Mode::from_config_str(
+        matches
+            .value_of("mode")
+            .or(config.server.mode.as_deref()),
+    );
+    let allow_insecure_fallback = matches.is_present("allow_insecure_fallback");
+
+    let results = resolve_batch(&config, mode, allow_insecure_fallback, &queries).await;
+
+    if matches.value_of("output") == Some("json") {
+        let target = config.server.target.clone();
+        let proxy = config.server.proxy.clone();
+        let documents: Vec<QueryResult> = queries
+            .iter()
+            .zip(results)
+            .map(|((domain, qtype), result)| {
+                QueryResult::new(domain, qtype, &target, proxy.as_deref(), result)
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&documents)?);
+    } else {
+        for ((domain, qtype), result) in queries.iter().zip(results) {
+            match result {
+                Ok(message) => print_answers(domain, qtype, &message),
+                Err(e) => eprintln!("{}\t{}\tquery failed: {}", domain, qtype, e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A single resolved answer record, in the shape emitted by `--output json`.
+#[derive(Serialize)]
+struct AnswerRecord {
+    name: String,
+    record_type: String,
+    ttl: u32,
+    rdata: String,
+}
+
+/// The JSON document emitted per query under `--output json`: the query
+/// metadata (domain, type, target/proxy used) alongside its answers or
+/// error, so many lookups can be emitted as a single JSON array.
+#[derive(Serialize)]
+struct QueryResult {
+    domain: String,
+    query_type: String,
+    target: String,
+    proxy: Option<String>,
+    answers: Vec<AnswerRecord>,
+    error: Option<String>,
+}
+
+impl QueryResult {
+    fn new(
+        domain: &str,
+        qtype: &str,
+        target: &str,
+        proxy: Option<&str>,
+        result: Result<Message>,
+    ) -> Self {
+        let (answers, error) = match result {
+            Ok(message) => (
+                message
+                    .answers()
+                    .iter()
+                    .map(|record| AnswerRecord {
+                        name: record.name().to_string(),
+                        record_type: record.record_type().to_string(),
+                        ttl: record.ttl(),
+                        rdata: format!("{:?}", record.rdata()),
+                    })
+                    .collect(),
+                None,
+            ),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        QueryResult {
+            domain: domain.to_string(),
+            query_type: qtype.to_string(),
+            target: target.to_string(),
+            proxy: proxy.map(str::to_string),
+            answers,
+            error,
+        }
+    }
+}
 
-    let message = session.resolve(domain, qtype).await?;
+/// Parse a file of "domain type" pairs, one per line. Blank lines and lines
+/// starting with `#` are ignored.
+fn read_queries_file(path: &str) -> Result<Vec<(String, String)>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read queries file {}", path))?;
+    parse_queries(&contents)
+}
+
+/// Parse the contents of a queries file, as read by `read_queries_file`.
+fn parse_queries(contents: &str) -> Result<Vec<(String, String)>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            let domain = parts
+                .next()
+                .with_context(|| format!("malformed query line: {}", line))?;
+            let qtype = parts
+                .next()
+                .with_context(|| format!("malformed query line: {}", line))?;
+            Ok((domain.to_string(), qtype.to_string()))
+        })
+        .collect()
+}
+
+fn print_answers(domain: &str, qtype: &str, message: &Message) {
     let answers = message.answers();
     if answers.is_empty() {
         println!("No result found for domain {}!", domain)
@@ -66,5 +270,212 @@ async fn main() -> Result<()> {
             println!("\t{}\t{:?}", record.name(), record.rdata())
         }
     }
-    Ok(())
+}
+
+/// How a DoH query may react to failure. Distinct from a plain bool so the
+/// error message can tell a user who forgot `--allow-insecure-fallback`
+/// apart from one who passed it against an explicit `--mode doh`, which is a
+/// hard floor and never cascades regardless of the flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DohFallback {
+    /// Fall through to plain DNS on failure.
+    Allow,
+    /// Return an error suggesting `--allow-insecure-fallback`.
+    Deny,
+    /// `--mode doh` was requested explicitly: never fall through, and say
+    /// so rather than suggesting a flag that wouldn't change the behavior.
+    HardFloor,
+}
+
+/// Resolve a batch of `(domain, qtype)` queries concurrently. When
+/// `mode` is `Mode::Odoh`, a single `ODOHSession` is built up front and
+/// reused for every query via `resolve_many` so they share one
+/// `reqwest::Client` connection pool and the cached target config. Per-query
+/// failures (both a session that can't be built, and a query that fails
+/// inside an established session) fall through to DoH/plain DNS exactly as
+/// they would in `resolve`, when `allow_insecure_fallback` is set.
+async fn resolve_batch(
+    config: &Config,
+    mode: Mode,
+    allow_insecure_fallback: bool,
+    queries: &[(String, String)],
+) -> Vec<Result<Message>> {
+    if mode == Mode::Odoh {
+        let config_ttl = config.server.config_ttl_secs.map(Duration::from_secs);
+        let session = ODOHSession::new(
+            config.server.target.as_str(),
+            config.server.proxy.as_deref(),
+            config_ttl,
+            config.server.padding_block,
+        )
+        .await;
+        if let Ok(session) = session {
+            let results = session.resolve_many(queries).await;
+            if !allow_insecure_fallback {
+                return results;
+            }
+            return join_all(queries.iter().zip(results).map(|((domain, qtype), result)| async move {
+                match result {
+                    Ok(message) => Ok(message),
+                    Err(_) => resolve_doh(config, DohFallback::Allow, domain, qtype).await,
+                }
+            }))
+            .await;
+        }
+    }
+    join_all(
+        queries
+            .iter()
+            .map(|(domain, qtype)| resolve(config, mode, allow_insecure_fallback, domain, qtype)),
+    )
+    .await
+}
+
+/// Resolve `domain`/`qtype` using the requested mode. `Mode::Odoh` only
+/// degrades to standard DoH (and then to plain DNS) when
+/// `allow_insecure_fallback` is set — this applies both when the ODoH
+/// session can't be established and when an established session fails to
+/// resolve this particular query. `Mode::Doh` never cascades to plain DNS,
+/// regardless of `allow_insecure_fallback` — an explicit `--mode doh` is a
+/// floor, not a preference.
+async fn resolve(
+    config: &Config,
+    mode: Mode,
+    allow_insecure_fallback: bool,
+    domain: &str,
+    qtype: &str,
+) -> Result<Message> {
+    if mode == Mode::Odoh {
+        let config_ttl = config.server.config_ttl_secs.map(Duration::from_secs);
+        let fallback = if allow_insecure_fallback {
+            DohFallback::Allow
+        } else {
+            DohFallback::Deny
+        };
+        match ODOHSession::new(
+            config.server.target.as_str(),
+            config.server.proxy.as_deref(),
+            config_ttl,
+            config.server.padding_block,
+        )
+        .await
+        {
+            Ok(session) => match session.resolve(domain, qtype).await {
+                Ok(message) => return Ok(message),
+                Err(e) => {
+                    if allow_insecure_fallback {
+                        return resolve_doh(config, fallback, domain, qtype).await;
+                    }
+                    return Err(e).context(
+                        "ODoH query failed; pass --allow-insecure-fallback to degrade to DoH/plain DNS",
+                    );
+                }
+            },
+            Err(e) => {
+                if allow_insecure_fallback {
+                    return resolve_doh(config, fallback, domain, qtype).await;
+                }
+                return Err(e).context(
+                    "failed to establish ODoH session; pass --allow-insecure-fallback to degrade to DoH/plain DNS",
+                );
+            }
+        }
+    }
+    if mode == Mode::Doh {
+        return resolve_doh(config, DohFallback::HardFloor, domain, qtype).await;
+    }
+    resolve_plain(config, domain, qtype).await
+}
+
+/// Perform a standard DoH query, reacting to failure as directed by
+/// `fallback`.
+async fn resolve_doh(
+    config: &Config,
+    fallback: DohFallback,
+    domain: &str,
+    qtype: &str,
+) -> Result<Message> {
+    let dns_msg = create_dns_query(domain, qtype)?;
+    match query_doh(config.server.target.as_str(), &dns_msg).await {
+        Ok(raw) => parse_dns_answer(&raw),
+        Err(e) => match fallback {
+            DohFallback::Allow => resolve_plain(config, domain, qtype).await,
+            DohFallback::Deny => Err(e).context(
+                "DoH query failed; pass --allow-insecure-fallback to degrade to plain DNS",
+            ),
+            DohFallback::HardFloor => Err(e).context(
+                "DoH query failed; --mode doh is a hard floor and never falls back to plain \
+                 DNS, regardless of --allow-insecure-fallback",
+            ),
+        },
+    }
+}
+
+async fn resolve_plain(config: &Config, domain: &str, qtype: &str) -> Result<Message> {
+    let resolver: SocketAddr = config
+        .server
+        .resolver
+        .as_deref()
+        .unwrap_or(DEFAULT_PLAIN_RESOLVER)
+        .parse()
+        .context("invalid resolver address")?;
+    let dns_msg = create_dns_query(domain, qtype)?;
+    let raw = query_plain(resolver, &dns_msg).await?;
+    parse_dns_answer(&raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_queries_skips_blank_and_comment_lines() {
+        let contents = "\nexample.com A\n# comment\n  \nexample.org AAAA\n";
+        let queries = parse_queries(contents).unwrap();
+        assert_eq!(
+            queries,
+            vec![
+                ("example.com".to_string(), "A".to_string()),
+                ("example.org".to_string(), "AAAA".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_queries_rejects_malformed_line() {
+        assert!(parse_queries("example.com").is_err());
+    }
+
+    #[test]
+    fn query_result_json_shape_on_success() {
+        let result = QueryResult::new(
+            "example.com",
+            "A",
+            "https://target.example/",
+            Some("https://proxy.example/"),
+            Ok(Message::new()),
+        );
+        let value = serde_json::to_value(&result).unwrap();
+        assert_eq!(value["domain"], "example.com");
+        assert_eq!(value["query_type"], "A");
+        assert_eq!(value["target"], "https://target.example/");
+        assert_eq!(value["proxy"], "https://proxy.example/");
+        assert_eq!(value["answers"], serde_json::json!([]));
+        assert!(value["error"].is_null());
+    }
+
+    #[test]
+    fn query_result_json_shape_on_error() {
+        let result = QueryResult::new(
+            "example.com",
+            "A",
+            "https://target.example/",
+            None,
+            Err(anyhow::anyhow!("query failed")),
+        );
+        let value = serde_json::to_value(&result).unwrap();
+        assert!(value["proxy"].is_null());
+        assert_eq!(value["answers"], serde_json::json!([]));
+        assert_eq!(value["error"], "query failed");
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
 use odoh_rs::protocol::{
     create_query_msg, get_supported_config, parse_received_response, ObliviousDoHConfigContents,
     ObliviousDoHQueryBody, ODOH_HTTP_HEADER,
@@ -7,6 +8,8 @@ use reqwest::{
     header::{HeaderMap, ACCEPT, CACHE_CONTROL, CONTENT_TYPE},
     Client, StatusCode,
 };
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 use trust_dns_proto::op::Message;
 use url::Url;
 
@@ -14,11 +17,55 @@ use crate::dns_utils::{create_dns_query, fetch_odoh_config, parse_dns_answer};
 
 const QUERY_PATH: &str = "/dns-query";
 
-#[derive(Clone, Debug)]
+/// How long a fetched ODoH config is trusted before it is proactively
+/// refreshed, absent an explicit TTL from `Config`.
+const DEFAULT_CONFIG_TTL: Duration = Duration::from_secs(3600);
+
+/// Default padding block size, in bytes. Padding the plaintext query up to
+/// a multiple of this size hides its exact length from a malicious proxy
+/// or on-path observer.
+const DEFAULT_PADDING_BLOCK: usize = 128;
+
+/// Bytes of padding needed to round `msg_len` up to the next multiple of
+/// `block_size`.
+fn padding_len(msg_len: usize, block_size: usize) -> usize {
+    if block_size == 0 {
+        return 0;
+    }
+    match msg_len % block_size {
+        0 => 0,
+        remainder => block_size - remainder,
+    }
+}
+
+/// Resolution mode: full ODoH, standard DoH, or classic plain DNS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Odoh,
+    Doh,
+    Plain,
+}
+
+impl Mode {
+    pub fn from_config_str(value: Option<&str>) -> Self {
+        match value {
+            Some(v) if v.eq_ignore_ascii_case("doh") => Mode::Doh,
+            Some(v) if v.eq_ignore_ascii_case("plain") => Mode::Plain,
+            _ => Mode::Odoh,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct ODOHSession {
     pub target: Url,
+    /// Original target base URL, used to re-fetch the ODoH config on refresh.
+    target_origin: String,
     pub proxy: Option<Url>,
-    pub target_config: ObliviousDoHConfigContents,
+    target_config: RwLock<ObliviousDoHConfigContents>,
+    config_fetched_at: RwLock<Instant>,
+    config_ttl: Duration,
+    padding_block: usize,
     pub client: Client,
 }
 
@@ -34,8 +81,13 @@ pub struct ODOHResponse {
 }
 
 impl ODOHSession {
-    /// Create a new ClientSession
-    pub async fn new(target: &str, proxy: Option<&str>) -> Result<Self> {
+    /// Create a new `ODOHSession`.
+    pub async fn new(
+        target: &str,
+        proxy: Option<&str>,
+        config_ttl: Option<Duration>,
+        padding_block: Option<usize>,
+    ) -> Result<Self> {
         let mut target_url = Url::parse(target)?;
         target_url.set_path(QUERY_PATH);
         let proxy = if let Some(p) = proxy {
@@ -45,20 +97,46 @@ impl ODOHSession {
         };
         let odoh_config = fetch_odoh_config(target).await?;
         let target_config = get_supported_config(&odoh_config)?;
+        let client = Client::new();
         Ok(Self {
             target: target_url,
+            target_origin: target.to_string(),
             proxy,
-            target_config,
-            client: Client::new(),
+            target_config: RwLock::new(target_config),
+            config_fetched_at: RwLock::new(Instant::now()),
+            config_ttl: config_ttl.unwrap_or(DEFAULT_CONFIG_TTL),
+            padding_block: padding_block.unwrap_or(DEFAULT_PADDING_BLOCK),
+            client,
         })
     }
 
+    /// Re-fetch the target's ODoH config, e.g. after it rotates its HPKE
+    /// key, and reset the freshness clock.
+    async fn refresh_config(&self) -> Result<()> {
+        let odoh_config = fetch_odoh_config(&self.target_origin).await?;
+        let refreshed = get_supported_config(&odoh_config)?;
+        *self.target_config.write().unwrap() = refreshed;
+        *self.config_fetched_at.write().unwrap() = Instant::now();
+        Ok(())
+    }
+
+    /// Refresh the cached config if it is older than `config_ttl`.
+    async fn refresh_if_stale(&self) -> Result<()> {
+        let is_stale = self.config_fetched_at.read().unwrap().elapsed() >= self.config_ttl;
+        if is_stale {
+            self.refresh_config().await?;
+        }
+        Ok(())
+    }
+
     /// Create an oblivious query from a domain and query type
     pub fn create_request(&self, domain: &str, qtype: &str) -> Result<ODOHRequest> {
         // create a DNS message
         let dns_msg = create_dns_query(domain, qtype)?;
-        let query = ObliviousDoHQueryBody::new(&dns_msg, Some(1));
-        let (encrypted_query, client_secret) = create_query_msg(&self.target_config, &query)?;
+        let padding = padding_len(dns_msg.len(), self.padding_block);
+        let query = ObliviousDoHQueryBody::new(&dns_msg, Some(padding));
+        let target_config = self.target_config.read().unwrap();
+        let (encrypted_query, client_secret) = create_query_msg(&target_config, &query)?;
         Ok(ODOHRequest {
             query,
             client_secret,
@@ -120,10 +198,66 @@ impl ODOHSession {
         Ok(parse_dns_answer(&response_body.dns_msg)?)
     }
 
+    /// Resolve `domain`/`qtype`, retrying once with a freshly fetched ODoH
+    /// config if the response fails to decrypt (e.g. the target rotated its
+    /// HPKE key since the config was cached).
     pub async fn resolve(&self, domain: &str, qtype: &str) -> Result<Message> {
+        self.refresh_if_stale().await?;
         let request = self.create_request(domain, qtype)?;
         let response = self.send_request(request).await?;
-        let dns_message = self.parse_response(response)?;
-        Ok(dns_message)
+        match self.parse_response(response) {
+            Ok(message) => Ok(message),
+            Err(_) => {
+                self.refresh_config().await?;
+                let request = self.create_request(domain, qtype)?;
+                let response = self.send_request(request).await?;
+                self.parse_response(response)
+            }
+        }
+    }
+
+    /// Resolve several independent `(domain, qtype)` queries concurrently,
+    /// reusing this session's `reqwest::Client` connection pool and cached
+    /// target config.
+    pub async fn resolve_many(&self, queries: &[(String, String)]) -> Vec<Result<Message>> {
+        let resolves = queries
+            .iter()
+            .map(|(domain, qtype)| self.resolve(domain, qtype));
+        join_all(resolves).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_from_config_str_recognizes_doh_and_plain_case_insensitively() {
+        assert_eq!(Mode::from_config_str(Some("DoH")), Mode::Doh);
+        assert_eq!(Mode::from_config_str(Some("plain")), Mode::Plain);
+    }
+
+    #[test]
+    fn mode_from_config_str_defaults_to_odoh() {
+        assert_eq!(Mode::from_config_str(None), Mode::Odoh);
+        assert_eq!(Mode::from_config_str(Some("odoh")), Mode::Odoh);
+        assert_eq!(Mode::from_config_str(Some("bogus")), Mode::Odoh);
+    }
+
+    #[test]
+    fn padding_len_rounds_up_to_next_block() {
+        assert_eq!(padding_len(10, 128), 118);
+        assert_eq!(padding_len(127, 128), 1);
+    }
+
+    #[test]
+    fn padding_len_is_zero_on_exact_multiple() {
+        assert_eq!(padding_len(128, 128), 0);
+        assert_eq!(padding_len(0, 128), 0);
+    }
+
+    #[test]
+    fn padding_len_is_zero_for_zero_block_size() {
+        assert_eq!(padding_len(42, 0), 0);
     }
 }
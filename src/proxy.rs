@@ -0,0 +1,268 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use reqwest::header::{HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::Client;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+const ODOH_CONTENT_TYPE: &str = "application/oblivious-dns-message";
+
+/// Upper bound on the request body the relay will buffer. ODoH messages are
+/// small (a DNS query/response plus HPKE overhead); this is generous headroom
+/// against a client trying to exhaust relay memory with an oversized POST.
+const MAX_BODY_BYTES: u64 = 16 * 1024;
+
+/// Oblivious relay (proxy) between an ODoH client and an ODoH target.
+///
+/// The relay never sees the decrypted DNS query: it only forwards the
+/// opaque `application/oblivious-dns-message` body to the target named
+/// by the `targethost`/`targetpath` query parameters, reads the target's
+/// encrypted response in full and sends it back unmodified.
+pub struct ProxyServer {
+    listen: SocketAddr,
+    path: String,
+    client: Client,
+}
+
+impl ProxyServer {
+    pub fn new(listen: SocketAddr, path: String) -> Self {
+        Self {
+            listen,
+            path,
+            client: Client::new(),
+        }
+    }
+
+    /// Run the relay until the process is terminated.
+    pub async fn run(self) -> Result<()> {
+        let listen = self.listen;
+        let shared = Arc::new(self);
+        let make_svc = make_service_fn(move |_conn| {
+            let shared = shared.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let shared = shared.clone();
+                    async move { Ok::<_, Infallible>(shared.handle(req).await) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&listen)
+            .with_context(|| format!("failed to bind proxy listener on {}", listen))?
+            .serve(make_svc);
+        server.await.context("proxy server exited unexpectedly")?;
+        Ok(())
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        if req.method() != Method::POST {
+            return error_response(StatusCode::METHOD_NOT_ALLOWED, "only POST is supported");
+        }
+        if req.uri().path() != self.path {
+            return error_response(StatusCode::NOT_FOUND, "unknown path");
+        }
+        if !has_odoh_content_type(req.headers().get(CONTENT_TYPE))
+            || !accepts_odoh_content_type(req.headers().get(ACCEPT))
+        {
+            return error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected application/oblivious-dns-message",
+            );
+        }
+
+        let (target_host, target_path) = match parse_target(req.uri().query()) {
+            Some(t) => t,
+            None => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    "missing targethost/targetpath query parameters",
+                )
+            }
+        };
+
+        if let Some(declared_len) = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            if declared_len > MAX_BODY_BYTES {
+                return error_response(StatusCode::PAYLOAD_TOO_LARGE, "body too large");
+            }
+        }
+
+        let body = match read_capped_body(req.into_body(), MAX_BODY_BYTES).await {
+            Ok(b) => b,
+            Err(BodyReadError::TooLarge) => {
+                return error_response(StatusCode::PAYLOAD_TOO_LARGE, "body too large")
+            }
+            Err(BodyReadError::Failed) => {
+                return error_response(StatusCode::BAD_REQUEST, "failed to read body")
+            }
+        };
+
+        let target_url = format!("https://{}{}", target_host, target_path);
+        let upstream = self
+            .client
+            .post(&target_url)
+            .header(CONTENT_TYPE, ODOH_CONTENT_TYPE)
+            .header(ACCEPT, ODOH_CONTENT_TYPE)
+            .body(body)
+            .send()
+            .await;
+
+        match upstream {
+            Ok(resp) if resp.status().is_success() => {
+                let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::OK);
+                let bytes = match resp.bytes().await {
+                    Ok(b) => b,
+                    Err(_) => {
+                        return error_response(StatusCode::BAD_GATEWAY, "failed to read target response")
+                    }
+                };
+                Response::builder()
+                    .status(status)
+                    .header(CONTENT_TYPE, ODOH_CONTENT_TYPE)
+                    .body(Body::from(bytes.to_vec()))
+                    .unwrap_or_else(|_| error_response(StatusCode::BAD_GATEWAY, "bad upstream response"))
+            }
+            Ok(resp) => error_response(
+                StatusCode::BAD_GATEWAY,
+                &format!("target responded with {}", resp.status()),
+            ),
+            Err(_) => error_response(StatusCode::BAD_GATEWAY, "failed to reach target"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BodyReadError {
+    /// The body exceeded `max_len` before the stream ended.
+    TooLarge,
+    /// The underlying connection failed while reading the body.
+    Failed,
+}
+
+/// Read `body` into memory, bailing out as soon as more than `max_len` bytes
+/// have been seen rather than buffering an unbounded request.
+async fn read_capped_body(mut body: Body, max_len: u64) -> Result<Vec<u8>, BodyReadError> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|_| BodyReadError::Failed)?;
+        if buf.len() as u64 + chunk.len() as u64 > max_len {
+            return Err(BodyReadError::TooLarge);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+fn has_odoh_content_type(value: Option<&HeaderValue>) -> bool {
+    value
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.eq_ignore_ascii_case(ODOH_CONTENT_TYPE))
+        .unwrap_or(false)
+}
+
+fn accepts_odoh_content_type(value: Option<&HeaderValue>) -> bool {
+    value
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v == "*/*" || v.eq_ignore_ascii_case(ODOH_CONTENT_TYPE))
+        .unwrap_or(true)
+}
+
+fn parse_target(query: Option<&str>) -> Option<(String, String)> {
+    let query = query?;
+    let mut host = None;
+    let mut path = None;
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "targethost" => host = Some(value.into_owned()),
+            "targetpath" => path = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+    Some((host?, path.unwrap_or_else(|| "/".to_string())))
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message.to_string()))
+        .expect("static error response is always valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_odoh_content_type_matches_case_insensitively() {
+        let header = HeaderValue::from_static("Application/Oblivious-DNS-Message");
+        assert!(has_odoh_content_type(Some(&header)));
+    }
+
+    #[test]
+    fn has_odoh_content_type_rejects_other_and_missing() {
+        let header = HeaderValue::from_static("application/dns-message");
+        assert!(!has_odoh_content_type(Some(&header)));
+        assert!(!has_odoh_content_type(None));
+    }
+
+    #[test]
+    fn accepts_odoh_content_type_allows_wildcard_and_exact() {
+        assert!(accepts_odoh_content_type(Some(&HeaderValue::from_static(
+            "*/*"
+        ))));
+        assert!(accepts_odoh_content_type(Some(&HeaderValue::from_static(
+            ODOH_CONTENT_TYPE
+        ))));
+        assert!(!accepts_odoh_content_type(Some(&HeaderValue::from_static(
+            "application/json"
+        ))));
+    }
+
+    #[test]
+    fn accepts_odoh_content_type_defaults_to_true_when_absent() {
+        assert!(accepts_odoh_content_type(None));
+    }
+
+    #[test]
+    fn parse_target_extracts_host_and_path() {
+        let (host, path) = parse_target(Some("targethost=example.com&targetpath=%2Fdns-query"))
+            .expect("valid query should parse");
+        assert_eq!(host, "example.com");
+        assert_eq!(path, "/dns-query");
+    }
+
+    #[test]
+    fn parse_target_defaults_path_when_missing() {
+        let (host, path) =
+            parse_target(Some("targethost=example.com")).expect("host-only query should parse");
+        assert_eq!(host, "example.com");
+        assert_eq!(path, "/");
+    }
+
+    #[test]
+    fn parse_target_requires_host() {
+        assert!(parse_target(Some("targetpath=%2Fdns-query")).is_none());
+        assert!(parse_target(None).is_none());
+    }
+
+    #[tokio::test]
+    async fn read_capped_body_accepts_body_within_limit() {
+        let body = read_capped_body(Body::from("hello"), 16).await.unwrap();
+        assert_eq!(body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn read_capped_body_rejects_body_over_limit() {
+        let err = read_capped_body(Body::from("this is too long"), 4)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BodyReadError::TooLarge));
+    }
+}